@@ -0,0 +1,122 @@
+//! RENDER-based alpha compositing for transparent wallpaper output. This is only used when
+//! [`crate::composite_manager_running`] reports a compositor and the server offers an ARGB32
+//! visual; otherwise the pixmap filled by [`crate::image::upload_frame`] is published as the
+//! root window's background instead (see `root_pixmap.rs`).
+
+use x11rb::connection::Connection;
+use x11rb::errors::ReplyError;
+use x11rb::protocol::render::{self, ConnectionExt as _, Color, CreatePictureAux, PictOp};
+use x11rb::protocol::xproto::{
+    Colormap, ColormapAlloc, ConnectionExt as _, CreateWindowAux, Drawable, Pixmap, Screen,
+    Visualid, Window, WindowClass,
+};
+
+/// An RGB color to blend transparent frames over when nothing behind the window already
+/// provides one, e.g. while testing in Xephyr before the desktop's usual background is present.
+#[derive(Debug, Clone, Copy)]
+pub struct Background {
+    pub red: u16,
+    pub green: u16,
+    pub blue: u16,
+}
+
+/// Create the window paperview draws into: a depth-32 ARGB window on its own `AllocNone`
+/// colormap. Non-default-depth windows need an explicit colormap and `border_pixel`, or
+/// `create_window` fails with `BadMatch`.
+///
+/// Returns the colormap alongside the window -- it's a separate resource the window doesn't own,
+/// so the caller must `free_colormap` it when the window is torn down (see
+/// `main::render_wallpaper`'s `composite_window` teardown), the same way `RootPixmapPublisher`
+/// avoids leaking pixmaps frame-over-frame.
+pub fn create_argb_window(
+    conn: &impl Connection,
+    screen: &Screen,
+    depth: u8,
+    visual: Visualid,
+    width: u16,
+    height: u16,
+) -> Result<(Window, Colormap), ReplyError> {
+    let colormap = conn.generate_id()?;
+    conn.create_colormap(ColormapAlloc::NONE, colormap, screen.root, visual)?;
+
+    let window = conn.generate_id()?;
+    conn.create_window(
+        depth,
+        window,
+        screen.root,
+        0,
+        0,
+        width,
+        height,
+        0,
+        WindowClass::INPUT_OUTPUT,
+        visual,
+        &CreateWindowAux::default()
+            .background_pixel(0)
+            .border_pixel(0)
+            .colormap(colormap),
+    )?;
+    Ok((window, colormap))
+}
+
+/// Composite a `width`x`height` drawable (`src_pixmap`, already uploaded at `src_format`'s
+/// depth) over `background` and into `dst`, via a RENDER `Composite` with `PictOp::OVER`. The
+/// background is painted into `dst` first with a plain `FillRectangles`, since `OVER` blends the
+/// source's alpha against whatever is already there.
+pub fn composite_frame_over_background(
+    conn: &impl Connection,
+    src_pixmap: Pixmap,
+    src_format: render::Pictformat,
+    dst: Drawable,
+    dst_format: render::Pictformat,
+    width: u16,
+    height: u16,
+    background: Background,
+) -> Result<(), ReplyError> {
+    let src_picture = conn.generate_id()?;
+    conn.render_create_picture(
+        src_picture,
+        src_pixmap,
+        src_format,
+        &CreatePictureAux::default(),
+    )?;
+
+    let dst_picture = conn.generate_id()?;
+    conn.render_create_picture(dst_picture, dst, dst_format, &CreatePictureAux::default())?;
+
+    conn.render_fill_rectangles(
+        PictOp::SRC,
+        dst_picture,
+        Color {
+            red: background.red,
+            green: background.green,
+            blue: background.blue,
+            alpha: 0xffff,
+        },
+        &[render::Rectangle {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        }],
+    )?;
+
+    conn.render_composite(
+        PictOp::OVER,
+        src_picture,
+        0,
+        dst_picture,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+        width,
+        height,
+    )?;
+
+    conn.render_free_picture(src_picture)?;
+    conn.render_free_picture(dst_picture)?;
+    Ok(())
+}
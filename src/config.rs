@@ -0,0 +1,25 @@
+//! Minimal command-line configuration. paperview has no other flags yet, so this is a plain
+//! `std::env::args` scan rather than pulling in a CLI-parsing crate.
+
+use crate::visual::VisualOverride;
+
+/// A user-requested visual override, parsed from `--visual-id=<hex>` or
+/// `--visual-depth=<depth>`. Useful for debugging driver-specific visuals or locking paperview to
+/// a known-good visual on multi-visual servers.
+pub fn visual_override_from_args() -> Option<VisualOverride> {
+    for arg in std::env::args().skip(1) {
+        if let Some(value) = arg.strip_prefix("--visual-id=") {
+            let value = value.trim_start_matches("0x");
+            match u32::from_str_radix(value, 16) {
+                Ok(id) => return Some(VisualOverride::Id(id)),
+                Err(_) => eprintln!("warning: couldn't parse --visual-id value {:?}", value),
+            }
+        } else if let Some(value) = arg.strip_prefix("--visual-depth=") {
+            match value.parse() {
+                Ok(depth) => return Some(VisualOverride::Depth(depth)),
+                Err(_) => eprintln!("warning: couldn't parse --visual-depth value {:?}", value),
+            }
+        }
+    }
+    None
+}
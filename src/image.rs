@@ -0,0 +1,125 @@
+//! BMP frame loading and upload to the X server via `put_image`.
+
+use std::convert::TryInto;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use x11rb::connection::Connection;
+use x11rb::errors::ReplyError;
+use x11rb::protocol::xproto::{ConnectionExt as _, Drawable, Gcontext, ImageFormat};
+
+/// A decoded BMP frame, ready to be pushed to the server with `put_image`.
+pub struct Frame {
+    pub width: u16,
+    pub height: u16,
+    /// Pixel data in BGRX byte order, 4 bytes per pixel, top-down row order -- the layout
+    /// `put_image` expects for `ImageFormat::Z_PIXMAP` at depth 24 or 32.
+    pub data: Vec<u8>,
+}
+
+/// Parse the handful of BMP fields we need: dimensions and the raw pixel array. Only
+/// uncompressed 24bpp BMPs are supported, which is what paperview's bundled wallpapers use.
+pub fn load_bmp_frame(path: &Path) -> io::Result<Frame> {
+    let bytes = fs::read(path)?;
+    let err = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_owned());
+
+    if bytes.len() < 54 || &bytes[0..2] != b"BM" {
+        return Err(err("not a BMP file"));
+    }
+    let pixel_offset = u32::from_le_bytes(bytes[10..14].try_into().unwrap()) as usize;
+    let width = i32::from_le_bytes(bytes[18..22].try_into().unwrap());
+    let height = i32::from_le_bytes(bytes[22..26].try_into().unwrap());
+    let bpp = u16::from_le_bytes(bytes[28..30].try_into().unwrap());
+    let compression = u32::from_le_bytes(bytes[30..34].try_into().unwrap());
+    if bpp != 24 || compression != 0 {
+        return Err(err("only uncompressed 24bpp BMPs are supported"));
+    }
+
+    let width = width.unsigned_abs();
+    let top_down = height < 0;
+    let height = height.unsigned_abs();
+    if width == 0 || height == 0 {
+        return Err(err("BMP width and height must be non-zero"));
+    }
+    let row_stride = ((width as u64 * 3 + 3) / 4) * 4; // BMP rows are padded to 4-byte boundaries.
+
+    // The header's `pixel_offset`/`width`/`height` are untrusted input -- a truncated or lying
+    // file must not be allowed to index past the end of `bytes`. Do the size arithmetic in u64
+    // so a huge header value errors out instead of overflowing and wrapping back in range.
+    let required_len = pixel_offset as u64 + row_stride * height as u64;
+    if required_len > bytes.len() as u64 {
+        return Err(err("pixel data runs past the end of the file"));
+    }
+
+    let mut data = vec![0u8; width as usize * height as usize * 4];
+    for row in 0..height {
+        let src_row = if top_down { row } else { height - 1 - row };
+        let src_start = pixel_offset + (src_row as u64 * row_stride) as usize;
+        let dst_row = row as usize * width as usize * 4;
+        for col in 0..width as usize {
+            let src = &bytes[src_start + col * 3..src_start + col * 3 + 3];
+            let dst = &mut data[dst_row + col * 4..dst_row + col * 4 + 4];
+            // BMP stores BGR; Z_PIXMAP wants the same byte order with a padding byte.
+            dst[0] = src[0];
+            dst[1] = src[1];
+            dst[2] = src[2];
+            dst[3] = 0;
+        }
+    }
+
+    Ok(Frame {
+        width: width as u16,
+        height: height as u16,
+        data,
+    })
+}
+
+/// Upload a decoded frame to `drawable` at `(dst_x, dst_y)`, at the given depth, via `put_image`.
+/// `depth` should be whatever `choose_visual` picked -- on a reduced-depth server this is 24, not
+/// 32, and getting it wrong corrupts the image instead of erroring.
+pub fn upload_frame(
+    conn: &impl Connection,
+    drawable: Drawable,
+    gc: Gcontext,
+    depth: u8,
+    dst_x: i16,
+    dst_y: i16,
+    frame: &Frame,
+) -> Result<(), ReplyError> {
+    conn.put_image(
+        ImageFormat::Z_PIXMAP,
+        drawable,
+        gc,
+        frame.width,
+        frame.height,
+        dst_x,
+        dst_y,
+        0,
+        depth,
+        &frame.data,
+    )?
+    .check()?;
+    Ok(())
+}
+
+/// Tile (or crop, if `frame` is already larger) `frame` to exactly `width`x`height`, by sampling
+/// modulo the source dimensions. Used to give each monitor a same-sized slice of one frame
+/// sequence when there isn't a dedicated sequence per monitor.
+pub fn tile_frame(frame: &Frame, width: u16, height: u16) -> Frame {
+    let mut data = vec![0u8; width as usize * height as usize * 4];
+    for row in 0..height as usize {
+        let src_row = row % frame.height as usize;
+        for col in 0..width as usize {
+            let src_col = col % frame.width as usize;
+            let src_start = (src_row * frame.width as usize + src_col) * 4;
+            let dst_start = (row * width as usize + col) * 4;
+            data[dst_start..dst_start + 4].copy_from_slice(&frame.data[src_start..src_start + 4]);
+        }
+    }
+    Frame {
+        width,
+        height,
+        data,
+    }
+}
@@ -7,14 +7,27 @@
 //!   which we provide an inline definition below.
 //!   (Alternatively, one could use `xcb::Visualtype` from the xcb crate; it's equivalent.)
 
+mod composite;
+mod config;
+mod image;
+mod monitors;
+mod render_cache;
+mod root_pixmap;
+mod visual;
+
 use x11rb::atom_manager;
 use x11rb::connection::Connection;
-use x11rb::errors::{ReplyError};
-use x11rb::protocol::render::{self, ConnectionExt as _, PictType};
+use x11rb::errors::ReplyError;
 use x11rb::protocol::xproto::{ConnectionExt as _, *};
+use x11rb::protocol::Event;
 use x11rb::xcb_ffi::XCBConnection;
 use std::{fs, path::{Path, PathBuf}};
 
+use image::Frame;
+use monitors::Monitor;
+use render_cache::{has_render, PictFormatCache};
+use visual::resolve_visual;
+
 // A collection of the atoms we will need.
 atom_manager! {
     pub AtomCollection: AtomCollectionCookie {
@@ -22,78 +35,11 @@ atom_manager! {
         WM_DELETE_WINDOW,
         _NET_WM_NAME,
         UTF8_STRING,
+        _XROOTPMAP_ID,
+        ESETROOT_PMAP_ID,
     }
 }
 
-/// A rust version of XCB's `xcb_visualtype_t` struct. This is used in a FFI-way.
-#[derive(Debug, Clone, Copy)]
-#[repr(C)]
-pub struct xcb_visualtype_t {
-    pub visual_id: u32,
-    pub class: u8,
-    pub bits_per_rgb_value: u8,
-    pub colormap_entries: u16,
-    pub red_mask: u32,
-    pub green_mask: u32,
-    pub blue_mask: u32,
-    pub pad0: [u8; 4],
-}
-
-impl From<Visualtype> for xcb_visualtype_t {
-    fn from(value: Visualtype) -> xcb_visualtype_t {
-        xcb_visualtype_t {
-            visual_id: value.visual_id,
-            class: value.class.into(),
-            bits_per_rgb_value: value.bits_per_rgb_value,
-            colormap_entries: value.colormap_entries,
-            red_mask: value.red_mask,
-            green_mask: value.green_mask,
-            blue_mask: value.blue_mask,
-            pad0: [0; 4],
-        }
-    }
-}
-
-/// Choose a visual to use. This function tries to find a depth=32 visual and falls back to the
-/// screen's default visual.
-fn choose_visual(conn: &impl Connection, screen_num: usize) -> Result<(u8, Visualid), ReplyError> {
-    let depth = 32;
-    let screen = &conn.setup().roots[screen_num];
-
-    // Try to use XRender to find a visual with alpha support
-    let has_render = conn
-        .extension_information(render::X11_EXTENSION_NAME)?
-        .is_some();
-    if has_render {
-        let formats = conn.render_query_pict_formats()?.reply()?;
-        // Find the ARGB32 format that must be supported.
-        let format = formats
-            .formats
-            .iter()
-            .filter(|info| (info.type_, info.depth) == (PictType::DIRECT, depth))
-            .filter(|info| {
-                let d = info.direct;
-                (d.red_mask, d.green_mask, d.blue_mask, d.alpha_mask) == (0xff, 0xff, 0xff, 0xff)
-            })
-            .find(|info| {
-                let d = info.direct;
-                (d.red_shift, d.green_shift, d.blue_shift, d.alpha_shift) == (16, 8, 0, 24)
-            });
-        if let Some(format) = format {
-            // Now we need to find the visual that corresponds to this format
-            if let Some(visual) = formats.screens[screen_num]
-                .depths
-                .iter()
-                .flat_map(|d| &d.visuals)
-                .find(|v| v.format == format.id)
-            {
-                return Ok((format.depth, visual.visual));
-            }
-        }
-    }
-    Ok((screen.root_depth, screen.root_visual))
-}
-
 /// Check if a composite manager is running
 fn composite_manager_running(
     conn: &impl Connection,
@@ -132,19 +78,169 @@ fn read_bitmap_files(directory_path: &str) -> Vec<PathBuf> {
     bitmap_files
 }
 
+/// Query the current monitor layout, falling back to treating the whole root window as a single
+/// monitor when RandR is unavailable or reports no monitors configured (a bare Xephyr).
+fn load_monitors(
+    conn: &impl Connection,
+    screen: &Screen,
+    has_randr: bool,
+) -> Result<Vec<Monitor>, ReplyError> {
+    let monitors = if has_randr {
+        monitors::query_monitors(conn, screen.root)?
+    } else {
+        Vec::new()
+    };
+    Ok(if monitors.is_empty() {
+        vec![Monitor {
+            x: 0,
+            y: 0,
+            width: screen.width_in_pixels,
+            height: screen.height_in_pixels,
+        }]
+    } else {
+        monitors
+    })
+}
+
+/// Render `frame`, tiled to each monitor's rectangle, into the single root-sized surface and
+/// publish it -- via RENDER compositing if a compositor is running and an ARGB32 visual is
+/// available, or as the root window's background pixmap otherwise. Called both for the initial
+/// frame and again from the event loop below whenever monitor geometry changes.
+///
+/// `composite_window` tracks the ARGB window (and its dedicated colormap) created by a previous
+/// call so both can be freed before a new pair is created on rebuild, the same leak this function
+/// would otherwise have that `RootPixmapPublisher` (see `root_pixmap.rs`) was written to avoid
+/// for pixmaps.
+#[allow(clippy::too_many_arguments)]
+fn render_wallpaper(
+    conn: &impl Connection,
+    screen: &Screen,
+    atoms: &AtomCollection,
+    pict_cache: Option<&PictFormatCache>,
+    depth: u8,
+    visualid: Visualid,
+    transparency: bool,
+    monitors: &[Monitor],
+    frame: &Frame,
+    root_pixmap_publisher: &mut root_pixmap::RootPixmapPublisher,
+    composite_window: &mut Option<(Window, Colormap)>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let width = screen.width_in_pixels;
+    let height = screen.height_in_pixels;
+
+    if let Some((window, colormap)) = composite_window.take() {
+        conn.destroy_window(window)?;
+        conn.free_colormap(colormap)?;
+    }
+
+    // The pixmap is root-sized and created at whatever depth `choose_visual` picked, so the
+    // `put_image` calls below are never depth 32 on a server that only gave us a 24bpp visual.
+    let pixmap = conn.generate_id()?;
+    conn.create_pixmap(depth, pixmap, screen.root, width, height)?;
+    let gc = conn.generate_id()?;
+    conn.create_gc(gc, pixmap, &CreateGCAux::default())?;
+
+    // Each monitor gets a same-sized slice of the one frame sequence we loaded, tiled to fill its
+    // rectangle, composited into the single root-sized pixmap at its own offset.
+    for monitor in monitors {
+        let tile = image::tile_frame(frame, monitor.width, monitor.height);
+        image::upload_frame(conn, pixmap, gc, depth, monitor.x, monitor.y, &tile)?;
+    }
+
+    // The compositing path always needs its own dedicated depth-32 ARGB visual -- not whatever
+    // `resolve_visual` picked for `pixmap` above, which may have been forced to a non-alpha depth
+    // by a `--visual-depth`/`--visual-id` override. Without a real alpha channel on the
+    // destination, `Composite`/`OVER` just produces an opaque blit.
+    let argb = pict_cache.and_then(|c| c.find_argb32_visual());
+    let src_format = pict_cache.and_then(|c| c.find_visual_format(visualid));
+
+    let mut composited = false;
+    if transparency {
+        if let (Some((argb_visual, argb_format)), Some(src_format)) = (argb, src_format) {
+            // A compositor is running and the server offers an ARGB32 visual: draw into a
+            // depth-32 ARGB window and let RENDER blend the frame's alpha against a background
+            // instead of showing it opaque.
+            let (window, colormap) =
+                composite::create_argb_window(conn, screen, 32, argb_visual, width, height)?;
+            conn.map_window(window)?;
+            composite::composite_frame_over_background(
+                conn,
+                pixmap,
+                src_format,
+                window,
+                argb_format,
+                width,
+                height,
+                composite::Background {
+                    red: 0,
+                    green: 0,
+                    blue: 0,
+                },
+            )?;
+            conn.flush()?;
+            conn.free_gc(gc)?;
+            conn.free_pixmap(pixmap)?;
+            *composite_window = Some((window, colormap));
+            composited = true;
+        } else {
+            eprintln!(
+                "warning: no depth-32 ARGB visual available for RENDER compositing; falling back to root-pixmap pseudo-transparency"
+            );
+        }
+    }
+
+    if !composited {
+        // No compositor (or no usable ARGB32 visual): publish the frame as the root window's
+        // background pixmap so pseudo-transparent clients (rxvt-unicode and friends) can still
+        // sample it.
+        root_pixmap_publisher.set(
+            conn,
+            screen.root,
+            atoms._XROOTPMAP_ID,
+            atoms.ESETROOT_PMAP_ID,
+            pixmap,
+        )?;
+        conn.flush()?;
+        conn.free_gc(gc)?;
+        // `pixmap` is now owned by the root window's background; freeing it here would leave
+        // the background pointing at a destroyed resource. `RootPixmapPublisher` frees our own
+        // previous pixmap directly on the next call, so this only needs to outlive our own exit,
+        // not every future frame -- hence retaining it past disconnect so the next run (or a
+        // crash) can still adopt it.
+        conn.set_close_down_mode(CloseDown::RETAIN_PERMANENT)?;
+    }
+
+    Ok(())
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let image_dir = "/home/eatmynerds/repos/paperview/cyberpunk-bmp";
     println!("Loading images");
     let bitmap_files = dbg!(read_bitmap_files(image_dir));
 
-    println!("Loading monitors");
-
     let (conn, screen_num) = XCBConnection::connect(None)?;
     let screen = &conn.setup().roots[screen_num];
     println!("{:#?}", screen);
     let atoms = AtomCollection::new(&conn)?.reply()?;
-    let (mut width, mut height) = (100, 100);
-    let (depth, visualid) = choose_visual(&conn, screen_num)?;
+
+    let has_randr = monitors::has_randr(&conn)?;
+    if has_randr {
+        monitors::select_screen_change_events(&conn, screen.root)?;
+    }
+
+    println!("Loading monitors");
+    let mut monitors = load_monitors(&conn, screen, has_randr)?;
+    println!("Found {} monitor(s): {:?}", monitors.len(), monitors);
+
+    // Queried once and reused below for the frame-blit path, so reconfiguring the wallpaper
+    // (e.g. on a composite-manager restart) doesn't force a fresh `QueryPictFormats` round trip.
+    let pict_cache = if has_render(&conn)? {
+        Some(PictFormatCache::new(&conn, screen_num)?)
+    } else {
+        None
+    };
+    let visual_override = config::visual_override_from_args();
+    let (depth, visualid) = resolve_visual(&conn, screen_num, pict_cache.as_ref(), visual_override)?;
     println!("Using visual {:#x} with depth {}", visualid, depth);
 
     // Check if a composite manager is running. In a real application, we should also react to a
@@ -155,5 +251,57 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         transparency
     );
 
+    let mut root_pixmap_publisher = root_pixmap::RootPixmapPublisher::new();
+    let mut composite_window: Option<(Window, Colormap)> = None;
+
+    if let Some(first) = bitmap_files.first() {
+        let frame = image::load_bmp_frame(first)?;
+
+        render_wallpaper(
+            &conn,
+            screen,
+            &atoms,
+            pict_cache.as_ref(),
+            depth,
+            visualid,
+            transparency,
+            &monitors,
+            &frame,
+            &mut root_pixmap_publisher,
+            &mut composite_window,
+        )?;
+
+        // React to monitor hotplug at runtime: a `RRScreenChangeNotify` means the geometry we
+        // just composited against may no longer match reality, so re-query it and rebuild the
+        // surface. Only a server that actually advertises RandR ever delivers this event, since
+        // `select_screen_change_events` is the thing that subscribes to it above.
+        if has_randr {
+            loop {
+                let event = conn.wait_for_event()?;
+                if let Event::RandrScreenChangeNotify(_) = event {
+                    monitors = load_monitors(&conn, screen, has_randr)?;
+                    println!(
+                        "Monitor layout changed, now {} monitor(s): {:?}",
+                        monitors.len(),
+                        monitors
+                    );
+                    render_wallpaper(
+                        &conn,
+                        screen,
+                        &atoms,
+                        pict_cache.as_ref(),
+                        depth,
+                        visualid,
+                        transparency,
+                        &monitors,
+                        &frame,
+                        &mut root_pixmap_publisher,
+                        &mut composite_window,
+                    )?;
+                }
+            }
+        }
+    }
+
     Ok(())
 }
@@ -0,0 +1,48 @@
+//! RandR monitor geometry: per-monitor rectangles used to composite each monitor's slice of the
+//! wallpaper at the correct offset within a single root-sized surface.
+
+use x11rb::connection::Connection;
+use x11rb::errors::ReplyError;
+use x11rb::protocol::randr::{self, ConnectionExt as _, NotifyMask};
+use x11rb::protocol::xproto::Window;
+
+/// One monitor's rectangle within the root window, as reported by `RRGetMonitors`.
+#[derive(Debug, Clone, Copy)]
+pub struct Monitor {
+    pub x: i16,
+    pub y: i16,
+    pub width: u16,
+    pub height: u16,
+}
+
+/// Query the current monitor layout.
+pub fn query_monitors(conn: &impl Connection, root: Window) -> Result<Vec<Monitor>, ReplyError> {
+    let reply = conn.randr_get_monitors(root, true)?.reply()?;
+    Ok(reply
+        .monitors
+        .into_iter()
+        .map(|m| Monitor {
+            x: m.x,
+            y: m.y,
+            width: m.width,
+            height: m.height,
+        })
+        .collect())
+}
+
+/// Subscribe to `RRScreenChangeNotify` on `root`. `main`'s event loop reacts to this by
+/// re-running `query_monitors` and rebuilding its composited surface via `render_wallpaper`.
+pub fn select_screen_change_events(
+    conn: &impl Connection,
+    root: Window,
+) -> Result<(), ReplyError> {
+    conn.randr_select_input(root, NotifyMask::SCREEN_CHANGE)?;
+    Ok(())
+}
+
+/// `extension_information` check for RandR, mirroring `render_cache::has_render`.
+pub fn has_randr(conn: &impl Connection) -> Result<bool, ReplyError> {
+    Ok(conn
+        .extension_information(randr::X11_EXTENSION_NAME)?
+        .is_some())
+}
@@ -0,0 +1,127 @@
+//! RENDER PictFormat cache, modeled on libXrender's renderutil (`XRenderFindStandardFormat`,
+//! `XRenderFindVisualFormat`). `QueryPictFormats` is fetched once and reused for every lookup,
+//! so reconfiguration events (monitor hotplug, composite-manager restart) don't re-issue the
+//! round trip.
+
+use x11rb::connection::Connection;
+use x11rb::errors::ReplyError;
+use x11rb::protocol::render::{
+    self, ConnectionExt as _, Pictforminfo, Pictformat, PictType, QueryPictFormatsReply,
+};
+use x11rb::protocol::xproto::Visualid;
+
+/// The server's well-known standard formats, as enumerated by libXrender's `PictStandard`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PictStandard {
+    Argb32,
+    Rgb24,
+    A8,
+    A4,
+    A1,
+}
+
+impl PictStandard {
+    /// The depth a format matching this standard must have.
+    fn depth(self) -> u8 {
+        match self {
+            PictStandard::Argb32 => 32,
+            PictStandard::Rgb24 => 24,
+            PictStandard::A8 => 8,
+            PictStandard::A4 => 4,
+            PictStandard::A1 => 1,
+        }
+    }
+
+    fn matches(self, info: &Pictforminfo) -> bool {
+        if info.type_ != PictType::DIRECT || info.depth != self.depth() {
+            return false;
+        }
+        let d = info.direct;
+        match self {
+            PictStandard::Argb32 => {
+                (d.red_mask, d.green_mask, d.blue_mask, d.alpha_mask) == (0xff, 0xff, 0xff, 0xff)
+                    && (d.red_shift, d.green_shift, d.blue_shift, d.alpha_shift)
+                        == (16, 8, 0, 24)
+            }
+            PictStandard::Rgb24 => {
+                (d.red_mask, d.green_mask, d.blue_mask) == (0xff, 0xff, 0xff)
+                    && (d.red_shift, d.green_shift, d.blue_shift) == (16, 8, 0)
+            }
+            PictStandard::A8 | PictStandard::A4 | PictStandard::A1 => {
+                (d.red_mask, d.green_mask, d.blue_mask) == (0, 0, 0)
+                    && d.alpha_mask == (1u16 << self.depth()) - 1
+            }
+        }
+    }
+}
+
+/// A cached copy of the server's `QueryPictFormats` reply, with the same lookups libXrender's
+/// renderutil provides.
+pub struct PictFormatCache {
+    reply: QueryPictFormatsReply,
+    screen_num: usize,
+}
+
+impl PictFormatCache {
+    /// Fetch `QueryPictFormats` once. Every lookup on the returned cache, including those made
+    /// after a reconfiguration event, answers from this one reply.
+    pub fn new(conn: &impl Connection, screen_num: usize) -> Result<Self, ReplyError> {
+        let reply = conn.render_query_pict_formats()?.reply()?;
+        Ok(Self { reply, screen_num })
+    }
+
+    fn info(&self, format: Pictformat) -> Option<&Pictforminfo> {
+        self.reply.formats.iter().find(|info| info.id == format)
+    }
+
+    /// Depth of a `Pictformat` previously returned by this cache.
+    pub fn depth(&self, format: Pictformat) -> Option<u8> {
+        self.info(format).map(|info| info.depth)
+    }
+
+    /// `XRenderFindStandardFormat`: the `Pictformat` for one of the server's well-known formats.
+    pub fn find_standard_format(&self, standard: PictStandard) -> Option<Pictformat> {
+        self.reply
+            .formats
+            .iter()
+            .find(|info| standard.matches(info))
+            .map(|info| info.id)
+    }
+
+    /// `XRenderFindVisualFormat`: the `Pictformat` a given visual renders through.
+    pub fn find_visual_format(&self, visual: Visualid) -> Option<Pictformat> {
+        self.reply.screens[self.screen_num]
+            .depths
+            .iter()
+            .flat_map(|d| &d.visuals)
+            .find(|v| v.visual == visual)
+            .map(|v| v.format)
+    }
+
+    /// The inverse of `find_visual_format`: the visual that renders through a given format.
+    pub fn find_format_for_visual(&self, format: Pictformat) -> Option<Visualid> {
+        self.reply.screens[self.screen_num]
+            .depths
+            .iter()
+            .flat_map(|d| &d.visuals)
+            .find(|v| v.format == format)
+            .map(|v| v.visual)
+    }
+
+    /// A visual and format pair for the server's ARGB32 standard format, independent of whatever
+    /// visual `choose_visual`/`resolve_visual` picked for plain frame uploads (which may have
+    /// been forced to a non-alpha depth by the user). Used by the RENDER compositing path, which
+    /// always needs a real alpha channel to blend against, not just whatever depth is current.
+    pub fn find_argb32_visual(&self) -> Option<(Visualid, Pictformat)> {
+        let format = self.find_standard_format(PictStandard::Argb32)?;
+        let visual = self.find_format_for_visual(format)?;
+        Some((visual, format))
+    }
+}
+
+/// True if the RENDER extension is present on this connection.
+pub fn has_render(conn: &impl Connection) -> Result<bool, ReplyError> {
+    Ok(conn
+        .extension_information(render::X11_EXTENSION_NAME)?
+        .is_some())
+}
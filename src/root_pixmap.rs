@@ -0,0 +1,86 @@
+//! Publishing the live wallpaper pixmap via `_XROOTPMAP_ID`/`ESETROOT_PMAP_ID`, the de-facto
+//! protocol pseudo-transparent clients (rxvt-unicode and friends) use to sample the desktop
+//! background when no compositor is blending things for them.
+
+use x11rb::connection::Connection;
+use x11rb::errors::ReplyError;
+use x11rb::protocol::xproto::{
+    AtomEnum, ChangeWindowAttributesAux, ConnectionExt as _, Pixmap, PropMode, Window,
+};
+
+/// Read the pixmap currently advertised under `atom` on `root`, if any.
+fn current_root_pixmap(
+    conn: &impl Connection,
+    root: Window,
+    atom: u32,
+) -> Result<Option<Pixmap>, ReplyError> {
+    let reply = conn
+        .get_property(false, root, atom, AtomEnum::PIXMAP, 0, 1)?
+        .reply()?;
+    Ok(reply.value32().and_then(|mut v| v.next()))
+}
+
+/// Publishes rendered frames as the root window's background pixmap, across however many
+/// frames the animation loop renders.
+///
+/// The first call may be adopting a pixmap a *previous, now-disconnected* run of paperview left
+/// behind (via `set_close_down_mode(RETAIN_PERMANENT)` before it exited) -- that resource isn't
+/// ours, so `kill_client` is the only way to reclaim it. Every call after that replaces a
+/// pixmap we created ourselves on this same connection, which `free_pixmap` handles directly;
+/// calling `kill_client` on our own resource there would instead kill our own connection.
+pub struct RootPixmapPublisher {
+    own_previous: Option<Pixmap>,
+    adopted_foreign: bool,
+}
+
+impl RootPixmapPublisher {
+    pub fn new() -> Self {
+        Self {
+            own_previous: None,
+            adopted_foreign: false,
+        }
+    }
+
+    /// Set `pixmap` as the root window's background and advertise it under `_XROOTPMAP_ID` and
+    /// `ESETROOT_PMAP_ID` so pseudo-transparent clients pick it up.
+    pub fn set(
+        &mut self,
+        conn: &impl Connection,
+        root: Window,
+        root_pmap_id: u32,
+        esetroot_pmap_id: u32,
+        pixmap: Pixmap,
+    ) -> Result<(), ReplyError> {
+        if !self.adopted_foreign {
+            self.adopted_foreign = true;
+            if let Some(old) = current_root_pixmap(conn, root, root_pmap_id)? {
+                if old != pixmap {
+                    conn.kill_client(old)?;
+                }
+            }
+        } else if let Some(old) = self.own_previous {
+            if old != pixmap {
+                conn.free_pixmap(old)?;
+            }
+        }
+
+        conn.change_window_attributes(
+            root,
+            &ChangeWindowAttributesAux::default().background_pixmap(pixmap),
+        )?;
+        conn.clear_area(false, root, 0, 0, 0, 0)?;
+
+        for atom in [root_pmap_id, esetroot_pmap_id] {
+            conn.change_property32(PropMode::REPLACE, root, atom, AtomEnum::PIXMAP, &[pixmap])?;
+        }
+
+        self.own_previous = Some(pixmap);
+        Ok(())
+    }
+}
+
+impl Default for RootPixmapPublisher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,148 @@
+//! Visual and depth selection.
+
+use x11rb::connection::Connection;
+use x11rb::errors::ReplyError;
+use x11rb::protocol::xproto::{Screen, Visualid, Visualtype};
+
+use crate::render_cache::{PictFormatCache, PictStandard};
+
+/// A rust version of XCB's `xcb_visualtype_t` struct. This is used in a FFI-way.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct xcb_visualtype_t {
+    pub visual_id: u32,
+    pub class: u8,
+    pub bits_per_rgb_value: u8,
+    pub colormap_entries: u16,
+    pub red_mask: u32,
+    pub green_mask: u32,
+    pub blue_mask: u32,
+    pub pad0: [u8; 4],
+}
+
+impl From<Visualtype> for xcb_visualtype_t {
+    fn from(value: Visualtype) -> xcb_visualtype_t {
+        xcb_visualtype_t {
+            visual_id: value.visual_id,
+            class: value.class.into(),
+            bits_per_rgb_value: value.bits_per_rgb_value,
+            colormap_entries: value.colormap_entries,
+            red_mask: value.red_mask,
+            green_mask: value.green_mask,
+            blue_mask: value.blue_mask,
+            pad0: [0; 4],
+        }
+    }
+}
+
+/// Standard formats we'll accept, most to least preferred. 24 and 32bpp are bit-for-bit
+/// compatible on the R/G/B channels, so falling back from ARGB32 to RGB24 never changes how
+/// colors are packed -- only whether there's a usable alpha channel.
+const CANDIDATE_STANDARDS: [PictStandard; 2] = [PictStandard::Argb32, PictStandard::Rgb24];
+
+/// Choose a visual to use. This walks `CANDIDATE_STANDARDS` from most to least preferred,
+/// looking each one up in `cache` and mapping it to a visual, and falls back to the screen's
+/// default visual if the server offers no RENDER-backed candidate at all (no RENDER extension,
+/// or `cache` is `None` because `has_render` came back false when the caller built it).
+///
+/// Takes the cache by reference rather than querying for one itself so that the frame-blit path
+/// can reuse the exact same `QueryPictFormats` reply instead of re-issuing the round trip.
+pub fn choose_visual(
+    conn: &impl Connection,
+    screen_num: usize,
+    cache: Option<&PictFormatCache>,
+) -> Result<(u8, Visualid), ReplyError> {
+    let screen = &conn.setup().roots[screen_num];
+
+    if let Some(cache) = cache {
+        for &standard in &CANDIDATE_STANDARDS {
+            let Some(format) = cache.find_standard_format(standard) else {
+                continue;
+            };
+            if let Some(visual) = cache.find_format_for_visual(format) {
+                // `find_standard_format` only just resolved `format`, so its depth is known.
+                let depth = cache.depth(format).expect("format came from this cache");
+                return Ok((depth, visual));
+            }
+        }
+    }
+    Ok((screen.root_depth, screen.root_visual))
+}
+
+/// A user-requested visual override, by id or by depth.
+#[derive(Debug, Clone, Copy)]
+pub enum VisualOverride {
+    Id(Visualid),
+    Depth(u8),
+}
+
+/// Depths `image::upload_frame` can actually paint: it always writes 4-byte BGRX pixels via
+/// `put_image`, which is only a valid `Z_PIXMAP` layout at 24 or 32bpp. A forced depth outside
+/// this set would sail through to `create_pixmap`/`upload_frame` and either corrupt the output or
+/// fail with a protocol error instead of being rejected up front like an unsupported depth is.
+fn is_upload_compatible_depth(depth: u8) -> bool {
+    matches!(depth, 24 | 32)
+}
+
+/// The first visual offered at exactly `depth`, ignoring RENDER entirely. Used when the user
+/// forces a depth instead of letting `choose_visual`'s heuristic pick one.
+pub fn find_visual_for_depth(screen: &Screen, depth: u8) -> Option<Visualid> {
+    screen
+        .allowed_depths
+        .iter()
+        .find(|d| d.depth == depth)
+        .and_then(|d| d.visuals.first())
+        .map(|v| v.visual_id)
+}
+
+/// The depth and id of the visual matching `id` exactly, if the server offers one.
+pub fn find_visual_by_id(screen: &Screen, id: Visualid) -> Option<(u8, Visualid)> {
+    screen.allowed_depths.iter().find_map(|d| {
+        d.visuals
+            .iter()
+            .find(|v| v.visual_id == id)
+            .map(|v| (d.depth, v.visual_id))
+    })
+}
+
+/// Resolve the visual to use, honoring a user-requested `override_` when the server can actually
+/// satisfy it, and otherwise falling back to `choose_visual`'s heuristic with a warning --
+/// mirroring rxvt's `select_visual`/`select_depth` fallback behavior.
+pub fn resolve_visual(
+    conn: &impl Connection,
+    screen_num: usize,
+    cache: Option<&PictFormatCache>,
+    override_: Option<VisualOverride>,
+) -> Result<(u8, Visualid), ReplyError> {
+    let screen = &conn.setup().roots[screen_num];
+    match override_ {
+        Some(VisualOverride::Id(id)) => match find_visual_by_id(screen, id) {
+            Some((depth, visual)) if is_upload_compatible_depth(depth) => {
+                return Ok((depth, visual))
+            }
+            Some((depth, _)) => eprintln!(
+                "warning: requested visual {:#x} has depth {}, which upload_frame can't paint (only 24 or 32bpp); falling back to the default heuristic",
+                id, depth
+            ),
+            None => eprintln!(
+                "warning: requested visual {:#x} not offered by this server, falling back to the default heuristic",
+                id
+            ),
+        },
+        Some(VisualOverride::Depth(depth)) if !is_upload_compatible_depth(depth) => {
+            eprintln!(
+                "warning: requested depth {} can't be painted by upload_frame (only 24 or 32bpp are supported), falling back to the default heuristic",
+                depth
+            );
+        }
+        Some(VisualOverride::Depth(depth)) => match find_visual_for_depth(screen, depth) {
+            Some(visual) => return Ok((depth, visual)),
+            None => eprintln!(
+                "warning: requested depth {} not offered by this server, falling back to the default heuristic",
+                depth
+            ),
+        },
+        None => {}
+    }
+    choose_visual(conn, screen_num, cache)
+}